@@ -0,0 +1,256 @@
+//! Lets a camera blend between several [`EnvironmentMapLight`]s instead of
+//! only ever sampling one, so transitions between zones (e.g. walking from
+//! outdoors into a cave) cross-fade instead of popping.
+//!
+//! A camera's own [`EnvironmentMapLight`] (if any) is always the first layer;
+//! additional layers are child entities that each carry their own
+//! `EnvironmentMapLight` and an [`EnvironmentMapWeight`].
+
+use bevy_app::{App, Plugin, Update};
+use bevy_core_pipeline::prelude::Camera3d;
+use bevy_ecs::{entity::EntityHashMap, prelude::*, query::With};
+use bevy_hierarchy::Children;
+use bevy_math::Mat4;
+use bevy_reflect::Reflect;
+use bevy_render::{
+    render_asset::RenderAssets,
+    render_resource::{binding_types::*, *},
+    renderer::{RenderDevice, RenderQueue},
+    texture::{FallbackImageCubemap, Image},
+    Extract, ExtractSchedule, Render, RenderApp, RenderSet,
+};
+use bevy_time::Time;
+
+use super::EnvironmentMapLight;
+
+/// Maximum number of environment maps that can be blended for a single
+/// camera at once, since `binding_array` requires a fixed size.
+pub const MAX_ENVIRONMENT_MAP_LAYERS: usize = 4;
+
+/// How strongly a layer's [`EnvironmentMapLight`] contributes to the blend,
+/// relative to the other active layers on the same camera. Weights are
+/// normalized before use, so e.g. `1.0` and `3.0` behave the same as `0.25`
+/// and `0.75`.
+#[derive(Component, Clone, Copy, Reflect)]
+pub struct EnvironmentMapWeight(pub f32);
+
+impl Default for EnvironmentMapWeight {
+    fn default() -> Self {
+        Self(1.0)
+    }
+}
+
+/// Animates [`EnvironmentMapWeight`] towards `target` at `speed` units per
+/// second, added by [`EnvironmentMapBlendPlugin`] so zone transitions can
+/// cross-fade by simply changing `target`.
+#[derive(Component, Clone, Copy, Reflect)]
+pub struct EnvironmentMapWeightTarget {
+    pub target: f32,
+    pub speed: f32,
+}
+
+fn animate_environment_map_weights(
+    time: Res<Time>,
+    mut query: Query<(&mut EnvironmentMapWeight, &EnvironmentMapWeightTarget)>,
+) {
+    for (mut weight, target) in &mut query {
+        let max_delta = target.speed * time.delta_seconds();
+        weight.0 += (target.target - weight.0).clamp(-max_delta, max_delta);
+    }
+}
+
+pub struct EnvironmentMapBlendPlugin;
+
+impl Plugin for EnvironmentMapBlendPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<EnvironmentMapWeight>()
+            .register_type::<EnvironmentMapWeightTarget>()
+            .add_systems(Update, animate_environment_map_weights);
+
+        let Some(render_app) = app.get_sub_app_mut(RenderApp) else {
+            return;
+        };
+        render_app
+            .init_resource::<ExtractedEnvironmentMapLayers>()
+            .init_resource::<EnvironmentMapBlendBuffers>()
+            .add_systems(ExtractSchedule, extract_environment_map_layers)
+            .add_systems(
+                Render,
+                prepare_environment_map_blend_buffers.in_set(RenderSet::Prepare),
+            );
+    }
+}
+
+struct ExtractedEnvironmentMapLayer {
+    diffuse: bevy_asset::Handle<Image>,
+    specular: bevy_asset::Handle<Image>,
+    intensity: f32,
+    rotation: bevy_math::Quat,
+    weight: f32,
+}
+
+impl ExtractedEnvironmentMapLayer {
+    fn new(layer: &EnvironmentMapLight, weight: Option<&EnvironmentMapWeight>) -> Self {
+        Self {
+            diffuse: layer.diffuse_map.clone(),
+            specular: layer.specular_map.clone(),
+            intensity: layer.intensity,
+            rotation: layer.rotation,
+            weight: weight.map_or(1.0, |weight| weight.0),
+        }
+    }
+}
+
+#[derive(Resource, Default)]
+struct ExtractedEnvironmentMapLayers(EntityHashMap<Vec<ExtractedEnvironmentMapLayer>>);
+
+fn extract_environment_map_layers(
+    mut extracted: ResMut<ExtractedEnvironmentMapLayers>,
+    cameras: Extract<
+        Query<
+            (
+                Entity,
+                Option<&EnvironmentMapLight>,
+                Option<&EnvironmentMapWeight>,
+            ),
+            With<Camera3d>,
+        >,
+    >,
+    children_query: Extract<Query<&Children>>,
+    layers: Extract<Query<(&EnvironmentMapLight, Option<&EnvironmentMapWeight>)>>,
+) {
+    extracted.0.clear();
+
+    for (camera, primary, primary_weight) in &cameras {
+        let mut camera_layers = Vec::new();
+
+        if let Some(primary) = primary {
+            camera_layers.push(ExtractedEnvironmentMapLayer::new(primary, primary_weight));
+        }
+
+        if let Ok(children) = children_query.get(camera) {
+            for &child in children.iter() {
+                let Ok((layer, weight)) = layers.get(child) else {
+                    continue;
+                };
+                camera_layers.push(ExtractedEnvironmentMapLayer::new(layer, weight));
+            }
+        }
+
+        if !camera_layers.is_empty() {
+            extracted.0.insert(camera, camera_layers);
+        }
+    }
+}
+
+/// GPU-side per-layer blend data: each layer keeps its own rotation and
+/// intensity (mirroring [`super::EnvironmentMapUniform`]) so a child layer's
+/// skybox can be spun and scaled independently of the primary environment map.
+#[derive(Clone, Copy, ShaderType, Default)]
+struct GpuEnvironmentMapBlendLayer {
+    transform: Mat4,
+    intensity: f32,
+    weight: f32,
+}
+
+/// Per-camera GPU state for blended environment maps: a binding array of
+/// diffuse/specular cubemaps and the buffer of per-layer transforms,
+/// intensities and normalized weights the shader uses to blend them.
+#[derive(Default)]
+pub struct EnvironmentMapBlendBuffer {
+    pub diffuse_maps: Vec<TextureView>,
+    pub specular_maps: Vec<TextureView>,
+    layers: StorageBuffer<Vec<GpuEnvironmentMapBlendLayer>>,
+    count: UniformBuffer<u32>,
+}
+
+impl EnvironmentMapBlendBuffer {
+    pub fn layers_buffer(&self) -> &Buffer {
+        self.layers
+            .buffer()
+            .expect("prepare_environment_map_blend_buffers should have written the buffer")
+    }
+
+    pub fn count_buffer(&self) -> &Buffer {
+        self.count
+            .buffer()
+            .expect("prepare_environment_map_blend_buffers should have written the buffer")
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct EnvironmentMapBlendBuffers(pub EntityHashMap<EnvironmentMapBlendBuffer>);
+
+fn prepare_environment_map_blend_buffers(
+    mut buffers: ResMut<EnvironmentMapBlendBuffers>,
+    extracted: Res<ExtractedEnvironmentMapLayers>,
+    images: Res<RenderAssets<Image>>,
+    fallback_image_cubemap: Res<FallbackImageCubemap>,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+) {
+    buffers.0.clear();
+
+    for (&camera, layers) in &extracted.0 {
+        let mut buffer = EnvironmentMapBlendBuffer::default();
+        let mut gpu_layers = Vec::new();
+
+        for layer in layers.iter().take(MAX_ENVIRONMENT_MAP_LAYERS) {
+            let (Some(diffuse_map), Some(specular_map)) =
+                (images.get(&layer.diffuse), images.get(&layer.specular))
+            else {
+                continue;
+            };
+
+            buffer.diffuse_maps.push(diffuse_map.texture_view.clone());
+            buffer.specular_maps.push(specular_map.texture_view.clone());
+            gpu_layers.push(GpuEnvironmentMapBlendLayer {
+                transform: Mat4::from_quat(layer.rotation),
+                intensity: layer.intensity,
+                weight: layer.weight.max(0.0),
+            });
+        }
+
+        let count = gpu_layers.len() as u32;
+
+        let total_weight: f32 = gpu_layers.iter().map(|layer| layer.weight).sum();
+        if total_weight > 0.0 {
+            for layer in &mut gpu_layers {
+                layer.weight /= total_weight;
+            }
+        }
+
+        // The binding arrays are declared with a fixed size of
+        // `MAX_ENVIRONMENT_MAP_LAYERS`, so pad out any unused slots with the
+        // fallback cubemap rather than leaving the binding array short, which
+        // would fail bind group creation. The shader only reads the first
+        // `count` layers, so the padding's weight/transform never matters.
+        while buffer.diffuse_maps.len() < MAX_ENVIRONMENT_MAP_LAYERS {
+            buffer
+                .diffuse_maps
+                .push(fallback_image_cubemap.texture_view.clone());
+            buffer
+                .specular_maps
+                .push(fallback_image_cubemap.texture_view.clone());
+            gpu_layers.push(GpuEnvironmentMapBlendLayer::default());
+        }
+
+        *buffer.layers.get_mut() = gpu_layers;
+        buffer.layers.write_buffer(&render_device, &render_queue);
+        *buffer.count.get_mut() = count;
+        buffer.count.write_buffer(&render_device, &render_queue);
+
+        buffers.0.insert(camera, buffer);
+    }
+}
+
+pub fn get_environment_map_blend_bind_group_layout_entries() -> [BindGroupLayoutEntryBuilder; 4] {
+    [
+        texture_cube(TextureSampleType::Float { filterable: true })
+            .count(core::num::NonZeroU32::new(MAX_ENVIRONMENT_MAP_LAYERS as u32).unwrap()),
+        texture_cube(TextureSampleType::Float { filterable: true })
+            .count(core::num::NonZeroU32::new(MAX_ENVIRONMENT_MAP_LAYERS as u32).unwrap()),
+        storage_buffer_read_only::<Vec<GpuEnvironmentMapBlendLayer>>(false),
+        uniform_buffer::<u32>(false),
+    ]
+}
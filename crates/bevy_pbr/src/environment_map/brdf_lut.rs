@@ -0,0 +1,162 @@
+//! Bakes the split-sum approximation's environment BRDF term into a 2D lookup
+//! texture once at startup, so [`get_bindings`](super::get_bindings) can hand
+//! it to shaders alongside the prefiltered diffuse and specular maps.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use bevy_app::{App, Plugin, Startup};
+use bevy_asset::{load_internal_asset, Assets, Handle};
+use bevy_ecs::prelude::*;
+use bevy_render::{
+    extract_resource::{ExtractResource, ExtractResourcePlugin},
+    render_asset::{RenderAssetUsages, RenderAssets},
+    render_graph::{Node, NodeRunError, RenderGraphContext, RenderLabel},
+    render_resource::{binding_types::texture_storage_2d, *},
+    renderer::{RenderContext, RenderDevice},
+    texture::Image,
+    RenderApp,
+};
+
+pub const BRDF_LUT_SHADER_HANDLE: Handle<Shader> = Handle::weak_from_u128(9042667917871437802);
+
+/// Resolution of the baked BRDF lookup texture along both axes (NdotV and
+/// roughness).
+const BRDF_LUT_SIZE: u32 = 512;
+
+#[derive(RenderLabel, Debug, Clone, Hash, PartialEq, Eq)]
+pub struct BrdfLutBakeLabel;
+
+/// Handle to the baked `(NdotV, roughness) -> (scale, bias)` lookup texture,
+/// shared by every [`super::EnvironmentMapLight`].
+#[derive(Resource, Clone, ExtractResource)]
+pub struct EnvironmentBrdfLut(pub Handle<Image>);
+
+pub struct BrdfLutPlugin;
+
+impl Plugin for BrdfLutPlugin {
+    fn build(&self, app: &mut App) {
+        load_internal_asset!(
+            app,
+            BRDF_LUT_SHADER_HANDLE,
+            "brdf_lut.wgsl",
+            Shader::from_wgsl
+        );
+
+        app.add_systems(Startup, create_brdf_lut_image)
+            .add_plugins(ExtractResourcePlugin::<EnvironmentBrdfLut>::default());
+    }
+
+    fn finish(&self, app: &mut App) {
+        let Some(render_app) = app.get_sub_app_mut(RenderApp) else {
+            return;
+        };
+        render_app.init_resource::<BrdfLutPipeline>();
+
+        let mut render_graph = render_app
+            .world_mut()
+            .resource_mut::<bevy_render::render_graph::RenderGraph>();
+        render_graph.add_node(BrdfLutBakeLabel, BrdfLutBakeNode::default());
+    }
+}
+
+fn create_brdf_lut_image(mut commands: Commands, mut images: ResMut<Assets<Image>>) {
+    let mut image = Image::new_fill(
+        Extent3d {
+            width: BRDF_LUT_SIZE,
+            height: BRDF_LUT_SIZE,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        &[0, 0, 0, 0],
+        TextureFormat::Rg16Float,
+        RenderAssetUsages::RENDER_WORLD,
+    );
+    image.texture_descriptor.usage =
+        TextureUsages::TEXTURE_BINDING | TextureUsages::STORAGE_BINDING | TextureUsages::COPY_DST;
+
+    commands.insert_resource(EnvironmentBrdfLut(images.add(image)));
+}
+
+#[derive(Resource)]
+struct BrdfLutPipeline {
+    layout: BindGroupLayout,
+    pipeline: CachedComputePipelineId,
+}
+
+impl FromWorld for BrdfLutPipeline {
+    fn from_world(world: &mut World) -> Self {
+        let render_device = world.resource::<RenderDevice>();
+        let layout = render_device.create_bind_group_layout(
+            "brdf_lut_bake_layout",
+            &BindGroupLayoutEntries::single(
+                ShaderStages::COMPUTE,
+                texture_storage_2d(TextureFormat::Rg16Float, StorageTextureAccess::WriteOnly),
+            ),
+        );
+
+        let pipeline = world
+            .resource::<PipelineCache>()
+            .queue_compute_pipeline(ComputePipelineDescriptor {
+                label: Some("brdf_lut_bake_pipeline".into()),
+                layout: vec![layout.clone()],
+                push_constant_ranges: vec![],
+                shader: BRDF_LUT_SHADER_HANDLE,
+                shader_defs: vec![],
+                entry_point: "bake_brdf_lut".into(),
+            });
+
+        Self { layout, pipeline }
+    }
+}
+
+/// Render-graph node that dispatches the BRDF LUT bake exactly once, the
+/// first frame both the LUT's GPU texture and the bake pipeline are ready.
+#[derive(Default)]
+struct BrdfLutBakeNode {
+    done: AtomicBool,
+}
+
+impl Node for BrdfLutBakeNode {
+    fn run(
+        &self,
+        _graph: &mut RenderGraphContext,
+        render_context: &mut RenderContext,
+        world: &World,
+    ) -> Result<(), NodeRunError> {
+        if self.done.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+
+        let Some(lut) = world.get_resource::<EnvironmentBrdfLut>() else {
+            return Ok(());
+        };
+        let images = world.resource::<RenderAssets<Image>>();
+        let Some(gpu_image) = images.get(&lut.0) else {
+            return Ok(());
+        };
+
+        let pipeline_cache = world.resource::<PipelineCache>();
+        let pipeline = world.resource::<BrdfLutPipeline>();
+        let Some(pipeline_state) = pipeline_cache.get_compute_pipeline(pipeline.pipeline) else {
+            return Ok(());
+        };
+
+        let bind_group = world.resource::<RenderDevice>().create_bind_group(
+            "brdf_lut_bake_bind_group",
+            &pipeline.layout,
+            &BindGroupEntries::single(&gpu_image.texture_view),
+        );
+
+        let mut pass = render_context
+            .command_encoder()
+            .begin_compute_pass(&ComputePassDescriptor::default());
+        pass.set_pipeline(pipeline_state);
+        pass.set_bind_group(0, &bind_group, &[]);
+        let workgroups = BRDF_LUT_SIZE.div_ceil(8);
+        pass.dispatch_workgroups(workgroups, workgroups, 1);
+        drop(pass);
+
+        self.done.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+}
@@ -0,0 +1,365 @@
+//! Runtime prefiltering of a raw HDR environment cubemap into the diffuse
+//! irradiance and specular radiance maps consumed by [`super::EnvironmentMapLight`].
+//!
+//! This lets callers use [`EnvironmentMapLight::from_cubemap`] with a single
+//! unfiltered cubemap instead of pre-baking both maps offline with a tool like
+//! `KhronosGroup`'s glTF-IBL-Sampler.
+
+use std::sync::Mutex;
+
+use bevy_app::{App, Plugin};
+use bevy_asset::{load_internal_asset, AssetId, Assets, Handle};
+use bevy_ecs::prelude::*;
+use bevy_math::Quat;
+use bevy_render::{
+    render_asset::{RenderAssetUsages, RenderAssets},
+    render_graph::{Node, NodeRunError, RenderGraphContext, RenderLabel},
+    render_resource::{binding_types::*, *},
+    renderer::{RenderContext, RenderDevice},
+    texture::{FallbackImage, Image},
+    ExtractSchedule, MainWorld, Render, RenderApp, RenderSet,
+};
+use bevy_utils::{default, HashSet};
+
+use super::EnvironmentMapLight;
+
+pub const ENVIRONMENT_FILTER_SHADER_HANDLE: Handle<Shader> =
+    Handle::weak_from_u128(2976379301358362154);
+
+/// Number of mip levels baked into the generated specular map, and therefore
+/// the number of discrete roughness values that get their own GGX convolution.
+const SPECULAR_MIP_COUNT: u32 = 5;
+const SPECULAR_BASE_SIZE: u32 = 128;
+const DIFFUSE_SIZE: u32 = 32;
+
+#[derive(RenderLabel, Debug, Clone, Hash, PartialEq, Eq)]
+pub struct EnvironmentMapPrefilterLabel;
+
+/// One raw cubemap queued for GPU-side prefiltering, and the two internally
+/// created [`Image`] assets that the prefilter node will fill in once `source`
+/// has finished loading.
+#[derive(Clone)]
+pub struct PendingEnvironmentMapPrefilter {
+    pub source: Handle<Image>,
+    pub diffuse_map: Handle<Image>,
+    pub specular_map: Handle<Image>,
+}
+
+/// Queue of environment maps awaiting GPU prefiltering. Lives in both worlds:
+/// callers push onto the main-world copy via [`EnvironmentMapLight::from_cubemap`],
+/// and [`extract_environment_map_generation_queue`] drains it into the
+/// render-world copy each frame, where entries persist until
+/// [`EnvironmentMapPrefilterNode`] has prefiltered them.
+#[derive(Resource, Default)]
+pub struct EnvironmentMapGenerationQueue(pub Vec<PendingEnvironmentMapPrefilter>);
+
+/// Destination diffuse maps of [`PendingEnvironmentMapPrefilter`] entries that
+/// have already been prefiltered, so the node doesn't redo the (expensive)
+/// convolution every frame. Keyed by the destination rather than `source`, so
+/// two pending entries that happen to share one source cubemap (e.g. two
+/// `from_cubemap` calls reusing the same HDRI) don't shadow each other. A
+/// `Mutex` rather than plain interior mutability because
+/// [`EnvironmentMapPrefilterNode::run`] only gets a shared `&World`.
+#[derive(Resource, Default)]
+struct FinishedEnvironmentMapPrefilters(Mutex<HashSet<AssetId<Image>>>);
+
+pub struct EnvironmentMapGenerationPlugin;
+
+impl Plugin for EnvironmentMapGenerationPlugin {
+    fn build(&self, app: &mut App) {
+        load_internal_asset!(
+            app,
+            ENVIRONMENT_FILTER_SHADER_HANDLE,
+            "environment_filter.wgsl",
+            Shader::from_wgsl
+        );
+
+        app.init_resource::<EnvironmentMapGenerationQueue>();
+
+        let Some(render_app) = app.get_sub_app_mut(RenderApp) else {
+            return;
+        };
+
+        render_app
+            .init_resource::<EnvironmentMapGenerationQueue>()
+            .init_resource::<FinishedEnvironmentMapPrefilters>()
+            .add_systems(ExtractSchedule, extract_environment_map_generation_queue)
+            .add_systems(Render, queue_environment_map_prefilters.in_set(RenderSet::Queue));
+    }
+
+    fn finish(&self, app: &mut App) {
+        let Some(render_app) = app.get_sub_app_mut(RenderApp) else {
+            return;
+        };
+        render_app.init_resource::<EnvironmentMapFilterPipeline>();
+
+        let mut render_graph = render_app
+            .world_mut()
+            .resource_mut::<bevy_render::render_graph::RenderGraph>();
+        render_graph.add_node(EnvironmentMapPrefilterLabel, EnvironmentMapPrefilterNode);
+    }
+}
+
+impl EnvironmentMapLight {
+    /// Builds an [`EnvironmentMapLight`] from a single raw HDR environment
+    /// cubemap, prefiltering it into the diffuse irradiance and specular
+    /// radiance maps on the GPU instead of requiring them to be pre-baked
+    /// offline.
+    ///
+    /// The returned maps are blank until the source image finishes loading
+    /// and the prefilter pass has run once; [`EnvironmentMapLight::is_loaded`]
+    /// reports `false` until then.
+    pub fn from_cubemap(
+        images: &mut Assets<Image>,
+        queue: &mut EnvironmentMapGenerationQueue,
+        source: Handle<Image>,
+    ) -> Self {
+        let diffuse_map = images.add(new_storage_cubemap(DIFFUSE_SIZE, 1));
+        let specular_map = images.add(new_storage_cubemap(SPECULAR_BASE_SIZE, SPECULAR_MIP_COUNT));
+
+        queue.0.push(PendingEnvironmentMapPrefilter {
+            source,
+            diffuse_map: diffuse_map.clone(),
+            specular_map: specular_map.clone(),
+        });
+
+        Self {
+            diffuse_map,
+            specular_map,
+            intensity: 1.0,
+            rotation: Quat::IDENTITY,
+        }
+    }
+}
+
+/// Creates a blank cube [`Image`] with `STORAGE_BINDING` usage so the
+/// prefilter compute passes can write into it directly.
+fn new_storage_cubemap(size: u32, mip_level_count: u32) -> Image {
+    let mut image = Image::new_fill(
+        Extent3d {
+            width: size,
+            height: size,
+            depth_or_array_layers: 6,
+        },
+        TextureDimension::D2,
+        &[0, 0, 0, 0],
+        TextureFormat::Rgba16Float,
+        RenderAssetUsages::RENDER_WORLD,
+    );
+    image.texture_descriptor.mip_level_count = mip_level_count;
+    image.texture_descriptor.usage =
+        TextureUsages::TEXTURE_BINDING | TextureUsages::STORAGE_BINDING | TextureUsages::COPY_DST;
+    image.texture_view_descriptor = Some(TextureViewDescriptor {
+        dimension: Some(TextureViewDimension::Cube),
+        ..default()
+    });
+    image
+}
+
+#[derive(Resource)]
+struct EnvironmentMapFilterPipeline {
+    layout: BindGroupLayout,
+    specular_pipeline: CachedComputePipelineId,
+    diffuse_pipeline: CachedComputePipelineId,
+}
+
+impl FromWorld for EnvironmentMapFilterPipeline {
+    fn from_world(world: &mut World) -> Self {
+        let render_device = world.resource::<RenderDevice>();
+        let layout = render_device.create_bind_group_layout(
+            "environment_map_filter_layout",
+            &BindGroupLayoutEntries::sequential(
+                ShaderStages::COMPUTE,
+                (
+                    texture_cube(TextureSampleType::Float { filterable: true }),
+                    sampler(SamplerBindingType::Filtering),
+                    texture_storage_2d_array(
+                        TextureFormat::Rgba16Float,
+                        StorageTextureAccess::WriteOnly,
+                    ),
+                    uniform_buffer::<FilterUniform>(false),
+                ),
+            ),
+        );
+
+        let pipeline_cache = world.resource::<PipelineCache>();
+        let specular_pipeline = pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+            label: Some("environment_map_prefilter_specular".into()),
+            layout: vec![layout.clone()],
+            push_constant_ranges: vec![],
+            shader: ENVIRONMENT_FILTER_SHADER_HANDLE,
+            shader_defs: vec![],
+            entry_point: "prefilter_specular".into(),
+        });
+        let diffuse_pipeline = pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+            label: Some("environment_map_convolve_diffuse".into()),
+            layout: vec![layout.clone()],
+            push_constant_ranges: vec![],
+            shader: ENVIRONMENT_FILTER_SHADER_HANDLE,
+            shader_defs: vec![],
+            entry_point: "convolve_diffuse".into(),
+        });
+
+        Self {
+            layout,
+            specular_pipeline,
+            diffuse_pipeline,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ShaderType)]
+struct FilterUniform {
+    roughness: f32,
+}
+
+/// Drains the main-world [`EnvironmentMapGenerationQueue`] into the
+/// render-world copy, so newly-queued maps survive until
+/// [`EnvironmentMapPrefilterNode`] has prefiltered them instead of being
+/// overwritten every frame like a regular extracted resource.
+fn extract_environment_map_generation_queue(
+    mut main_world: ResMut<MainWorld>,
+    mut render_queue: ResMut<EnvironmentMapGenerationQueue>,
+) {
+    let mut main_queue = main_world.resource_mut::<EnvironmentMapGenerationQueue>();
+    render_queue.0.append(&mut main_queue.0);
+}
+
+/// Drops entries that [`EnvironmentMapPrefilterNode`] has already prefiltered.
+fn queue_environment_map_prefilters(
+    mut queue: ResMut<EnvironmentMapGenerationQueue>,
+    finished: Res<FinishedEnvironmentMapPrefilters>,
+) {
+    let finished = finished.0.lock().unwrap();
+    queue.0.retain(|pending| !finished.contains(&pending.diffuse_map.id()));
+}
+
+/// Render-graph node that runs the specular GGX and diffuse irradiance
+/// convolution compute passes once per queued environment map, the frame
+/// after its source cubemap finishes loading.
+struct EnvironmentMapPrefilterNode;
+
+impl Node for EnvironmentMapPrefilterNode {
+    fn run(
+        &self,
+        _graph: &mut RenderGraphContext,
+        render_context: &mut RenderContext,
+        world: &World,
+    ) -> Result<(), NodeRunError> {
+        let pipeline_cache = world.resource::<PipelineCache>();
+        let pipeline = world.resource::<EnvironmentMapFilterPipeline>();
+        let images = world.resource::<RenderAssets<Image>>();
+        let queue = world.resource::<EnvironmentMapGenerationQueue>();
+        let fallback = world.resource::<FallbackImage>();
+        let finished = world.resource::<FinishedEnvironmentMapPrefilters>();
+
+        let (Some(specular_state), Some(diffuse_state)) = (
+            pipeline_cache.get_compute_pipeline(pipeline.specular_pipeline),
+            pipeline_cache.get_compute_pipeline(pipeline.diffuse_pipeline),
+        ) else {
+            return Ok(());
+        };
+
+        let render_device = world.resource::<RenderDevice>();
+
+        for pending in &queue.0 {
+            if finished.0.lock().unwrap().contains(&pending.diffuse_map.id()) {
+                continue;
+            }
+
+            let (Some(source), Some(diffuse_map), Some(specular_map)) = (
+                images.get(&pending.source),
+                images.get(&pending.diffuse_map),
+                images.get(&pending.specular_map),
+            ) else {
+                continue;
+            };
+
+            let mut pass = render_context
+                .command_encoder()
+                .begin_compute_pass(&ComputePassDescriptor::default());
+
+            // Diffuse irradiance: a single small pass into the (mip-less) diffuse map.
+            // `diffuse_map.texture_view` is the asset's default Cube-shaped view (needed
+            // for `texture_cube` sampling in `get_bindings`), but storage texture bindings
+            // don't support Cube/CubeArray views, so bind a dedicated D2Array view instead.
+            let diffuse_storage_view = diffuse_map.texture.create_view(&TextureViewDescriptor {
+                dimension: Some(TextureViewDimension::D2Array),
+                ..default()
+            });
+            let diffuse_bind_group = self.pass_bind_group(
+                render_device,
+                &pipeline.layout,
+                &source.texture_view,
+                &fallback.d2.sampler,
+                &diffuse_storage_view,
+                0.0,
+            );
+            pass.set_pipeline(diffuse_state);
+            pass.set_bind_group(0, &diffuse_bind_group, &[]);
+            let diffuse_workgroups = DIFFUSE_SIZE.div_ceil(8);
+            pass.dispatch_workgroups(diffuse_workgroups, diffuse_workgroups, 6);
+
+            // Specular: one GGX convolution pass per mip, with roughness
+            // increasing linearly from 0 at the base mip to 1 at the last.
+            for mip in 0..SPECULAR_MIP_COUNT {
+                let roughness = mip as f32 / (SPECULAR_MIP_COUNT - 1) as f32;
+                let mip_size = (SPECULAR_BASE_SIZE >> mip).max(1);
+                let mip_view = specular_map.texture.create_view(&TextureViewDescriptor {
+                    base_mip_level: mip,
+                    mip_level_count: Some(1),
+                    dimension: Some(TextureViewDimension::D2Array),
+                    ..default()
+                });
+                let specular_bind_group = self.pass_bind_group(
+                    render_device,
+                    &pipeline.layout,
+                    &source.texture_view,
+                    &fallback.d2.sampler,
+                    &mip_view,
+                    roughness,
+                );
+                pass.set_pipeline(specular_state);
+                pass.set_bind_group(0, &specular_bind_group, &[]);
+                let workgroups = mip_size.div_ceil(8);
+                pass.dispatch_workgroups(workgroups, workgroups, 6);
+            }
+
+            drop(pass);
+            finished.0.lock().unwrap().insert(pending.diffuse_map.id());
+        }
+
+        Ok(())
+    }
+}
+
+impl EnvironmentMapPrefilterNode {
+    fn pass_bind_group(
+        &self,
+        render_device: &RenderDevice,
+        layout: &BindGroupLayout,
+        source_view: &TextureView,
+        source_sampler: &Sampler,
+        output_view: &TextureView,
+        roughness: f32,
+    ) -> BindGroup {
+        let mut uniform_buffer = encase::UniformBuffer::new(Vec::new());
+        uniform_buffer.write(&FilterUniform { roughness }).unwrap();
+        let buffer = render_device.create_buffer_with_data(&BufferInitDescriptor {
+            label: Some("environment_map_filter_uniform"),
+            contents: uniform_buffer.as_ref(),
+            usage: BufferUsages::UNIFORM,
+        });
+
+        render_device.create_bind_group(
+            "environment_map_filter_bind_group",
+            layout,
+            &BindGroupEntries::sequential((
+                source_view,
+                source_sampler,
+                output_view,
+                buffer.as_entire_binding(),
+            )),
+        )
+    }
+}
@@ -1,13 +1,38 @@
+mod blend;
+mod brdf_lut;
+mod generate;
+mod probe;
+mod uniform;
+
+pub use blend::{
+    get_environment_map_blend_bind_group_layout_entries, EnvironmentMapBlendBuffer,
+    EnvironmentMapBlendBuffers, EnvironmentMapWeight, EnvironmentMapWeightTarget,
+    MAX_ENVIRONMENT_MAP_LAYERS,
+};
+pub use brdf_lut::EnvironmentBrdfLut;
+pub use generate::{EnvironmentMapGenerationPlugin, EnvironmentMapGenerationQueue};
+pub use probe::{
+    get_reflection_probe_bind_group_layout_entries, get_reflection_probe_bindings,
+    ReflectionProbe, ReflectionProbeBounds, ReflectionProbeBuffer, MAX_REFLECTION_PROBES,
+};
+pub use uniform::{EnvironmentMapUniform, EnvironmentMapUniforms};
+
+use blend::EnvironmentMapBlendPlugin;
+use brdf_lut::BrdfLutPlugin;
+use probe::ReflectionProbePlugin;
+use uniform::EnvironmentMapUniformPlugin;
+
 use bevy_app::{App, Plugin};
 use bevy_asset::{load_internal_asset, Handle};
 use bevy_core_pipeline::prelude::Camera3d;
 use bevy_ecs::{prelude::Component, query::With};
+use bevy_math::Quat;
 use bevy_reflect::Reflect;
 use bevy_render::{
     extract_component::{ExtractComponent, ExtractComponentPlugin},
     render_asset::RenderAssets,
     render_resource::{
-        binding_types::{sampler, texture_cube},
+        binding_types::{sampler, texture_2d, texture_cube, uniform_buffer},
         *,
     },
     texture::{FallbackImageCubemap, Image},
@@ -28,7 +53,14 @@ impl Plugin for EnvironmentMapPlugin {
         );
 
         app.register_type::<EnvironmentMapLight>()
-            .add_plugins(ExtractComponentPlugin::<EnvironmentMapLight>::default());
+            .add_plugins((
+                ExtractComponentPlugin::<EnvironmentMapLight>::default(),
+                EnvironmentMapGenerationPlugin,
+                BrdfLutPlugin,
+                ReflectionProbePlugin,
+                EnvironmentMapUniformPlugin,
+                EnvironmentMapBlendPlugin,
+            ));
     }
 }
 
@@ -42,30 +74,74 @@ impl Plugin for EnvironmentMapPlugin {
 /// The environment map must be prefiltered into a diffuse and specular cubemap based on the
 /// [split-sum approximation](https://cdn2.unrealengine.com/Resources/files/2013SiggraphPresentationsNotes-26915738.pdf).
 ///
-/// To prefilter your environment map, you can use `KhronosGroup`'s [glTF-IBL-Sampler](https://github.com/KhronosGroup/glTF-IBL-Sampler).
-/// The diffuse map uses the Lambertian distribution, and the specular map uses the GGX distribution.
+/// To prefilter your environment map, you can use `KhronosGroup`'s [glTF-IBL-Sampler](https://github.com/KhronosGroup/glTF-IBL-Sampler),
+/// or build one directly from a raw HDR cubemap at runtime with [`EnvironmentMapLight::from_cubemap`],
+/// which prefilters both maps on the GPU instead. The diffuse map uses the Lambertian distribution,
+/// and the specular map uses the GGX distribution.
 ///
 /// `KhronosGroup` also has several prefiltered environment maps that can be found [here](https://github.com/KhronosGroup/glTF-Sample-Environments).
+///
+/// The split-sum approximation's other factor, the environment BRDF, doesn't depend on the
+/// environment map itself and is baked once at startup into a shared lookup texture; see
+/// [`EnvironmentBrdfLut`].
+///
+/// `EnvironmentMapLight` models light from infinitely distant scenery. For a reflection
+/// that's bounded to a region of the scene and parallax-corrected against nearby geometry,
+/// use [`ReflectionProbe`] instead.
+///
+/// A camera isn't limited to a single `EnvironmentMapLight`: attach additional ones, each
+/// with an [`EnvironmentMapWeight`], to child entities of the camera to cross-fade between
+/// them (e.g. while walking from outdoors into a cave) instead of popping between zones.
 #[derive(Component, Reflect, Clone, ExtractComponent)]
 #[extract_component_filter(With<Camera3d>)]
 pub struct EnvironmentMapLight {
     pub diffuse_map: Handle<Image>,
     pub specular_map: Handle<Image>,
+    /// Scales both the diffuse irradiance and specular contributions of this environment map.
+    ///
+    /// This is useful for reusing one baked environment map across areas with different
+    /// lighting intensities, or for matching its brightness to the rest of the lighting rig.
+    pub intensity: f32,
+    /// Rotates the sampling direction before the cubemap lookup.
+    ///
+    /// This allows a baked skybox to be spun in place to align its sun with a directional
+    /// light, without having to re-bake the environment map.
+    pub rotation: Quat,
 }
 
 impl EnvironmentMapLight {
-    /// Whether or not all textures necessary to use the environment map
-    /// have been loaded by the asset server.
-    pub fn is_loaded(&self, images: &RenderAssets<Image>) -> bool {
-        images.get(&self.diffuse_map).is_some() && images.get(&self.specular_map).is_some()
+    /// Whether or not all textures necessary to use the environment map have
+    /// been loaded by the asset server. For an [`EnvironmentMapLight`] built
+    /// with [`EnvironmentMapLight::from_cubemap`], this additionally waits
+    /// for the GPU prefilter pass to finish, since the destination maps are
+    /// uploaded (and thus "loaded") as blank placeholders well before that.
+    pub fn is_loaded(
+        &self,
+        images: &RenderAssets<Image>,
+        generation_queue: &EnvironmentMapGenerationQueue,
+    ) -> bool {
+        images.get(&self.diffuse_map).is_some()
+            && images.get(&self.specular_map).is_some()
+            && !generation_queue
+                .0
+                .iter()
+                .any(|pending| pending.diffuse_map.id() == self.diffuse_map.id())
     }
 }
 
 pub fn get_bindings<'a>(
     environment_map_light: Option<&EnvironmentMapLight>,
+    brdf_lut: &'a EnvironmentBrdfLut,
+    environment_map_uniforms: &'a EnvironmentMapUniforms,
     images: &'a RenderAssets<Image>,
     fallback_image_cubemap: &'a FallbackImageCubemap,
-) -> (&'a TextureView, &'a TextureView, &'a Sampler) {
+) -> (
+    &'a TextureView,
+    &'a TextureView,
+    &'a Sampler,
+    &'a TextureView,
+    &'a Buffer,
+) {
     let (diffuse_map, specular_map) = match (
         environment_map_light.and_then(|env_map| images.get(&env_map.diffuse_map)),
         environment_map_light.and_then(|env_map| images.get(&env_map.specular_map)),
@@ -79,13 +155,33 @@ pub fn get_bindings<'a>(
         ),
     };
 
-    (diffuse_map, specular_map, &fallback_image_cubemap.sampler)
+    // The LUT is baked once at startup and is always available after that,
+    // so unlike the per-light maps above it has no fallback path.
+    let brdf_lut = &images
+        .get(&brdf_lut.0)
+        .expect("BRDF LUT should have been baked at startup")
+        .texture_view;
+
+    let uniform_buffer = environment_map_uniforms
+        .uniforms()
+        .buffer()
+        .expect("EnvironmentMapUniform buffer should have been prepared");
+
+    (
+        diffuse_map,
+        specular_map,
+        &fallback_image_cubemap.sampler,
+        brdf_lut,
+        uniform_buffer,
+    )
 }
 
-pub fn get_bind_group_layout_entries() -> [BindGroupLayoutEntryBuilder; 3] {
+pub fn get_bind_group_layout_entries() -> [BindGroupLayoutEntryBuilder; 5] {
     [
         texture_cube(TextureSampleType::Float { filterable: true }),
         texture_cube(TextureSampleType::Float { filterable: true }),
         sampler(SamplerBindingType::Filtering),
+        texture_2d(TextureSampleType::Float { filterable: true }),
+        uniform_buffer::<EnvironmentMapUniform>(true),
     ]
 }
\ No newline at end of file
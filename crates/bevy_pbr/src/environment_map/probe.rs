@@ -0,0 +1,236 @@
+//! Local, spatially-bounded reflection probes.
+//!
+//! Unlike [`super::EnvironmentMapLight`], which represents light from
+//! infinitely distant scenery (a skybox), a [`ReflectionProbe`] is anchored to
+//! a region of the scene and its reflections are parallax-corrected against a
+//! bounding volume so they align with nearby geometry such as rooms or
+//! corridors.
+
+use bevy_app::{App, Plugin};
+use bevy_asset::Handle;
+use bevy_ecs::prelude::*;
+use bevy_math::{Affine3A, Mat4, Vec3};
+use bevy_reflect::Reflect;
+use bevy_render::{
+    render_asset::RenderAssets,
+    render_resource::{binding_types::*, *},
+    renderer::{RenderDevice, RenderQueue},
+    texture::{FallbackImageCubemap, Image},
+    Extract, ExtractSchedule, Render, RenderApp, RenderSet,
+};
+use bevy_transform::components::GlobalTransform;
+
+/// Maximum number of reflection probes that can be bound to the lighting
+/// shader at once, since `binding_array` requires a fixed size.
+pub const MAX_REFLECTION_PROBES: usize = 8;
+
+/// The shape of a [`ReflectionProbe`]'s bounding volume, used both to decide
+/// which probe covers a shaded fragment and to parallax-correct its
+/// reflection lookups.
+#[derive(Clone, Copy, Reflect)]
+pub enum ReflectionProbeBounds {
+    /// An axis-aligned or oriented box, in the probe's local space (i.e.
+    /// rotated and translated by the entity's [`GlobalTransform`]).
+    Box { half_extents: Vec3 },
+    /// A sphere of the given radius, centered on the entity's
+    /// [`GlobalTransform`] translation.
+    Sphere { radius: f32 },
+}
+
+/// A local reflection probe: a bounded region of the scene with its own
+/// prefiltered diffuse and specular cubemaps, used instead of the camera's
+/// global [`super::EnvironmentMapLight`] for fragments it contains.
+///
+/// The probe's center and orientation come from the entity's
+/// [`GlobalTransform`]; `bounds` is expressed relative to it.
+#[derive(Component, Clone, Reflect)]
+pub struct ReflectionProbe {
+    pub diffuse_map: Handle<Image>,
+    pub specular_map: Handle<Image>,
+    pub bounds: ReflectionProbeBounds,
+}
+
+pub struct ReflectionProbePlugin;
+
+impl Plugin for ReflectionProbePlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<ReflectionProbe>();
+
+        let Some(render_app) = app.get_sub_app_mut(RenderApp) else {
+            return;
+        };
+        render_app
+            .init_resource::<ExtractedReflectionProbes>()
+            .init_resource::<ReflectionProbeBuffer>()
+            .add_systems(ExtractSchedule, extract_reflection_probes)
+            .add_systems(Render, prepare_reflection_probes.in_set(RenderSet::Prepare));
+    }
+}
+
+#[derive(Clone, Copy, ShaderType, Default)]
+struct GpuReflectionProbe {
+    world_to_local: Mat4,
+    center: Vec3,
+    shape: u32,
+    half_extents: Vec3,
+    radius: f32,
+    diffuse_map_index: u32,
+    specular_map_index: u32,
+}
+
+const SHAPE_BOX: u32 = 0;
+const SHAPE_SPHERE: u32 = 1;
+
+struct ExtractedReflectionProbe {
+    transform: GlobalTransform,
+    diffuse_map: Handle<Image>,
+    specular_map: Handle<Image>,
+    bounds: ReflectionProbeBounds,
+}
+
+#[derive(Resource, Default)]
+struct ExtractedReflectionProbes(Vec<ExtractedReflectionProbe>);
+
+fn extract_reflection_probes(
+    mut extracted: ResMut<ExtractedReflectionProbes>,
+    probes: Extract<Query<(&GlobalTransform, &ReflectionProbe)>>,
+) {
+    extracted.0.clear();
+    extracted
+        .0
+        .extend(probes.iter().map(|(transform, probe)| ExtractedReflectionProbe {
+            transform: *transform,
+            diffuse_map: probe.diffuse_map.clone(),
+            specular_map: probe.specular_map.clone(),
+            bounds: probe.bounds,
+        }));
+}
+
+/// GPU-side representation of the active reflection probes: a fixed-size
+/// binding array of diffuse/specular cubemaps plus the buffer of probe
+/// transforms and bounds the shader uses to pick between them.
+#[derive(Resource, Default)]
+pub struct ReflectionProbeBuffer {
+    pub diffuse_maps: Vec<TextureView>,
+    pub specular_maps: Vec<TextureView>,
+    buffer: StorageBuffer<Vec<GpuReflectionProbe>>,
+    count: UniformBuffer<u32>,
+}
+
+impl ReflectionProbeBuffer {
+    pub fn probe_buffer(&self) -> &Buffer {
+        self.buffer
+            .buffer()
+            .expect("prepare_reflection_probes should have written the buffer")
+    }
+
+    pub fn count_buffer(&self) -> &Buffer {
+        self.count
+            .buffer()
+            .expect("prepare_reflection_probes should have written the buffer")
+    }
+}
+
+/// Gathers the bindings for the reflection probe bind group described by
+/// [`get_reflection_probe_bind_group_layout_entries`]. `sampler` is shared
+/// with the camera's global environment map, the same way
+/// [`super::get_bindings`] reuses the fallback cubemap's sampler.
+pub fn get_reflection_probe_bindings<'a>(
+    probe_buffer: &'a ReflectionProbeBuffer,
+    sampler: &'a Sampler,
+) -> (
+    &'a [TextureView],
+    &'a [TextureView],
+    &'a Sampler,
+    &'a Buffer,
+    &'a Buffer,
+) {
+    (
+        &probe_buffer.diffuse_maps,
+        &probe_buffer.specular_maps,
+        sampler,
+        probe_buffer.probe_buffer(),
+        probe_buffer.count_buffer(),
+    )
+}
+
+fn prepare_reflection_probes(
+    mut probe_buffer: ResMut<ReflectionProbeBuffer>,
+    extracted: Res<ExtractedReflectionProbes>,
+    images: Res<RenderAssets<Image>>,
+    fallback_image_cubemap: Res<FallbackImageCubemap>,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+) {
+    probe_buffer.diffuse_maps.clear();
+    probe_buffer.specular_maps.clear();
+
+    let mut gpu_probes = Vec::new();
+
+    for probe in extracted
+        .0
+        .iter()
+        .take(MAX_REFLECTION_PROBES)
+    {
+        let (Some(diffuse_map), Some(specular_map)) = (
+            images.get(&probe.diffuse_map),
+            images.get(&probe.specular_map),
+        ) else {
+            continue;
+        };
+
+        let index = gpu_probes.len() as u32;
+        let affine: Affine3A = probe.transform.affine();
+        let world_to_local = Mat4::from(affine.inverse());
+
+        let (shape, half_extents, radius) = match probe.bounds {
+            ReflectionProbeBounds::Box { half_extents } => (SHAPE_BOX, half_extents, 0.0),
+            ReflectionProbeBounds::Sphere { radius } => (SHAPE_SPHERE, Vec3::ZERO, radius),
+        };
+
+        gpu_probes.push(GpuReflectionProbe {
+            world_to_local,
+            center: probe.transform.translation(),
+            shape,
+            half_extents,
+            radius,
+            diffuse_map_index: index,
+            specular_map_index: index,
+        });
+
+        probe_buffer.diffuse_maps.push(diffuse_map.texture_view.clone());
+        probe_buffer.specular_maps.push(specular_map.texture_view.clone());
+    }
+
+    let probe_count = gpu_probes.len() as u32;
+
+    // The binding arrays are declared with a fixed size of `MAX_REFLECTION_PROBES`,
+    // so pad out any unused slots with the fallback cubemap rather than leaving
+    // the binding array short, which would fail bind group creation.
+    while probe_buffer.diffuse_maps.len() < MAX_REFLECTION_PROBES {
+        probe_buffer
+            .diffuse_maps
+            .push(fallback_image_cubemap.texture_view.clone());
+        probe_buffer
+            .specular_maps
+            .push(fallback_image_cubemap.texture_view.clone());
+    }
+
+    *probe_buffer.buffer.get_mut() = gpu_probes;
+    probe_buffer.buffer.write_buffer(&render_device, &render_queue);
+
+    *probe_buffer.count.get_mut() = probe_count;
+    probe_buffer.count.write_buffer(&render_device, &render_queue);
+}
+
+pub fn get_reflection_probe_bind_group_layout_entries() -> [BindGroupLayoutEntryBuilder; 5] {
+    [
+        texture_cube(TextureSampleType::Float { filterable: true })
+            .count(core::num::NonZeroU32::new(MAX_REFLECTION_PROBES as u32).unwrap()),
+        texture_cube(TextureSampleType::Float { filterable: true })
+            .count(core::num::NonZeroU32::new(MAX_REFLECTION_PROBES as u32).unwrap()),
+        sampler(SamplerBindingType::Filtering),
+        storage_buffer_read_only::<Vec<GpuReflectionProbe>>(false),
+        uniform_buffer::<u32>(false),
+    ]
+}
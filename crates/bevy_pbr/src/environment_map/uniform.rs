@@ -0,0 +1,53 @@
+//! The per-camera uniform that plumbs [`EnvironmentMapLight::intensity`] and
+//! [`EnvironmentMapLight::rotation`] into `environment_map.wgsl`.
+
+use bevy_app::{App, Plugin};
+use bevy_ecs::{prelude::*, query::QueryItem};
+use bevy_math::Mat4;
+use bevy_render::{
+    extract_component::{
+        ComponentUniforms, ExtractComponent, ExtractComponentPlugin, UniformComponentPlugin,
+    },
+    render_resource::ShaderType,
+};
+
+use super::EnvironmentMapLight;
+
+pub struct EnvironmentMapUniformPlugin;
+
+impl Plugin for EnvironmentMapUniformPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins((
+            ExtractComponentPlugin::<EnvironmentMapUniform>::default(),
+            UniformComponentPlugin::<EnvironmentMapUniform>::default(),
+        ));
+    }
+}
+
+/// GPU-side transform and intensity scale for an [`EnvironmentMapLight`],
+/// rebuilt from it every frame and uploaded as a dynamic-offset uniform.
+#[derive(Component, Clone, Copy, ShaderType)]
+pub struct EnvironmentMapUniform {
+    /// Rotation matrix applied to the sampling direction before the cubemap
+    /// lookup, letting artists spin a baked skybox to align the sun without
+    /// re-baking it.
+    pub transform: Mat4,
+    pub intensity: f32,
+}
+
+impl ExtractComponent for EnvironmentMapUniform {
+    type QueryData = &'static EnvironmentMapLight;
+    type QueryFilter = ();
+    type Out = Self;
+
+    fn extract_component(item: QueryItem<'_, Self::QueryData>) -> Option<Self::Out> {
+        Some(Self {
+            transform: Mat4::from_quat(item.rotation),
+            intensity: item.intensity,
+        })
+    }
+}
+
+/// Re-exported so callers extending [`super::get_bindings`] don't need to
+/// reach into `bevy_render` themselves for the dynamic uniform buffer.
+pub type EnvironmentMapUniforms = ComponentUniforms<EnvironmentMapUniform>;